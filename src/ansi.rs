@@ -0,0 +1,203 @@
+//! Parsing of ANSI CSI SGR escape sequences (`ESC [ <params> m`), the color
+//! codes emitted by colorized tools (`ls --color`, `grep --color`, log
+//! formatters). This is the inverse of ansi-stripping: escape bytes drive a
+//! running style rather than being discarded.
+
+use crate::width::clip_char_to_cols;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AnsiColor {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AnsiStyle {
+    pub fg: AnsiColor,
+    pub bg: AnsiColor,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+/// Parse CSI SGR escapes in `text`, returning styled runs for the printable
+/// columns in `[col_start, col_end)`. Escape bytes are consumed for their
+/// effect on the running style but never count as columns, so `col_start`/
+/// `col_end` line up with visible glyphs rather than raw byte offsets. Column
+/// clipping (tab stops, wide glyphs, straddling runs) is handled by
+/// `width::clip_char_to_cols`, shared with `file::File::clip_ranges_to_cols`.
+pub fn parse_sgr_line(text: &str, col_start: u64, col_end: u64) -> Vec<(AnsiStyle, String)> {
+    let mut spans: Vec<(AnsiStyle, String)> = vec![];
+    let mut style = AnsiStyle::default();
+    let mut cur = String::new();
+    let mut col: u64 = 0;
+
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == 0x1b && bytes.get(i + 1) == Some(&b'[') {
+            let mut j = i + 2;
+            while j < bytes.len() && (bytes[j].is_ascii_digit() || bytes[j] == b';') {
+                j += 1;
+            }
+            if j < bytes.len() && bytes[j] == b'm' {
+                let new_style = apply_sgr_params(style, &text[i + 2..j]);
+                if new_style != style && !cur.is_empty() {
+                    spans.push((style, std::mem::take(&mut cur)));
+                }
+                style = new_style;
+                i = j + 1;
+                continue;
+            }
+        }
+
+        let ch = text[i..].chars().next().unwrap();
+        col = clip_char_to_cols(&mut cur, ch, col, col_start, col_end);
+        i += ch.len_utf8();
+    }
+
+    if !cur.is_empty() {
+        spans.push((style, cur));
+    }
+
+    spans
+}
+
+fn apply_sgr_params(mut style: AnsiStyle, params: &str) -> AnsiStyle {
+    let codes: Vec<&str> = params.split(';').collect();
+    let mut k = 0;
+    while k < codes.len() {
+        let code: i32 = codes[k].parse().unwrap_or(0);
+        match code {
+            0 => style = AnsiStyle::default(),
+            1 => style.bold = true,
+            3 => style.italic = true,
+            4 => style.underline = true,
+            7 => style.reverse = true,
+            22 => style.bold = false,
+            23 => style.italic = false,
+            24 => style.underline = false,
+            27 => style.reverse = false,
+            39 => style.fg = AnsiColor::Default,
+            49 => style.bg = AnsiColor::Default,
+            30..=37 => style.fg = AnsiColor::Indexed((code - 30) as u8),
+            90..=97 => style.fg = AnsiColor::Indexed((code - 90 + 8) as u8),
+            40..=47 => style.bg = AnsiColor::Indexed((code - 40) as u8),
+            100..=107 => style.bg = AnsiColor::Indexed((code - 100 + 8) as u8),
+            38 | 48 => {
+                let is_fg = code == 38;
+                let mode: i32 = codes.get(k + 1).and_then(|s| s.parse().ok()).unwrap_or(0);
+                if mode == 5 {
+                    if let Some(n) = codes.get(k + 2).and_then(|s| s.parse().ok()) {
+                        let color = AnsiColor::Indexed(n);
+                        if is_fg {
+                            style.fg = color;
+                        } else {
+                            style.bg = color;
+                        }
+                    }
+                    k += 2;
+                } else if mode == 2 {
+                    if let (Some(r), Some(g), Some(b)) = (
+                        codes.get(k + 2).and_then(|s| s.parse().ok()),
+                        codes.get(k + 3).and_then(|s| s.parse().ok()),
+                        codes.get(k + 4).and_then(|s| s.parse().ok()),
+                    ) {
+                        let color = AnsiColor::Rgb(r, g, b);
+                        if is_fg {
+                            style.fg = color;
+                        } else {
+                            style.bg = color;
+                        }
+                    }
+                    k += 4;
+                }
+            }
+            _ => {}
+        }
+        k += 1;
+    }
+    style
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_is_one_unstyled_span() {
+        let spans = parse_sgr_line("hello", 0, 100);
+        assert_eq!(spans, vec![(AnsiStyle::default(), "hello".to_string())]);
+    }
+
+    #[test]
+    fn color_escape_starts_a_new_span() {
+        let spans = parse_sgr_line("\x1b[31mred\x1b[0mplain", 0, 100);
+        let red = AnsiStyle {
+            fg: AnsiColor::Indexed(1),
+            ..AnsiStyle::default()
+        };
+        assert_eq!(
+            spans,
+            vec![
+                (red, "red".to_string()),
+                (AnsiStyle::default(), "plain".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiple_params_in_one_escape_combine() {
+        let spans = parse_sgr_line("\x1b[1;32mbold green", 0, 100);
+        let bold_green = AnsiStyle {
+            fg: AnsiColor::Indexed(2),
+            bold: true,
+            ..AnsiStyle::default()
+        };
+        assert_eq!(spans, vec![(bold_green, "bold green".to_string())]);
+    }
+
+    #[test]
+    fn extended_256_color_param() {
+        let spans = parse_sgr_line("\x1b[38;5;200mfoo", 0, 100);
+        let style = AnsiStyle {
+            fg: AnsiColor::Indexed(200),
+            ..AnsiStyle::default()
+        };
+        assert_eq!(spans, vec![(style, "foo".to_string())]);
+    }
+
+    #[test]
+    fn extended_rgb_color_param() {
+        let spans = parse_sgr_line("\x1b[48;2;10;20;30mfoo", 0, 100);
+        let style = AnsiStyle {
+            bg: AnsiColor::Rgb(10, 20, 30),
+            ..AnsiStyle::default()
+        };
+        assert_eq!(spans, vec![(style, "foo".to_string())]);
+    }
+
+    #[test]
+    fn escape_bytes_do_not_count_as_columns() {
+        let spans = parse_sgr_line("\x1b[31mred\x1b[0m", 0, 2);
+        let red = AnsiStyle {
+            fg: AnsiColor::Indexed(1),
+            ..AnsiStyle::default()
+        };
+        assert_eq!(spans, vec![(red, "re".to_string())]);
+    }
+
+    #[test]
+    fn unterminated_escape_is_left_as_is() {
+        // No final `m`, so this isn't a valid SGR escape and gets walked
+        // character by character instead of being silently swallowed: the
+        // zero-width ESC byte itself is clipped like any other zero-width
+        // char, but the printable bytes that follow come through as-is.
+        let spans = parse_sgr_line("\x1b[31", 0, 100);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].1, "[31");
+    }
+}
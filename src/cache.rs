@@ -0,0 +1,238 @@
+//! On-disk cache of the line index built by [`crate::file::File::build_linemap`]
+//! (the `line_to_byte_idx`/`line_to_num_bytes`/`line_to_num_cols` vectors plus
+//! `num_lines`/`max_num_cols`), keyed by canonical path, file size, and mtime.
+//! This lets a multi-gigabyte file reopen instantly instead of waiting on the
+//! background scan, the same way a compiler's incremental cache keys on a
+//! source file's identity rather than reparsing it to check for changes.
+//!
+//! Only the index is cached, not syntax-highlight state: on a cache hit the
+//! background line scan is skipped, and highlight checkpoints are rebuilt
+//! from the cached line map by `File::build_highlight_checkpoints` in the
+//! background; until they exist, highlighted rendering falls back to plain
+//! text (see `File::highlight_line`).
+
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub(crate) struct CacheKey {
+    canonical_path: String,
+    len: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: CacheKey,
+    num_lines: u64,
+    max_num_cols: u64,
+    line_to_byte_idx: Vec<u64>,
+    line_to_num_bytes: Vec<u64>,
+    line_to_num_cols: Vec<u64>,
+}
+
+pub struct CachedIndex {
+    pub num_lines: u64,
+    pub max_num_cols: u64,
+    pub line_to_byte_idx: Vec<u64>,
+    pub line_to_num_bytes: Vec<u64>,
+    pub line_to_num_cols: Vec<u64>,
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME") {
+        return Some(PathBuf::from(dir).join("glance"));
+    }
+    let home = std::env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".cache").join("glance"))
+}
+
+fn key_for(path: &Path) -> Result<CacheKey, Error> {
+    let canonical = std::fs::canonicalize(path)?;
+    let meta = std::fs::metadata(&canonical)?;
+    let since_epoch = meta.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(CacheKey {
+        canonical_path: canonical.to_string_lossy().into_owned(),
+        len: meta.len(),
+        mtime_secs: since_epoch.as_secs(),
+        mtime_nanos: since_epoch.subsec_nanos(),
+    })
+}
+
+/// Build a cache key for `path` using `len` from an already-taken mmap
+/// snapshot rather than a live re-stat, so the key records the size this
+/// scan actually read instead of whatever the file has grown to by the time
+/// the scan finishes and calls `save`. `mtime` still comes from an fstat of
+/// `file_handle` taken at the same snapshot point.
+pub(crate) fn key_for_snapshot(
+    path: &Path,
+    file_handle: &std::fs::File,
+    len: u64,
+) -> Result<CacheKey, Error> {
+    let canonical = std::fs::canonicalize(path)?;
+    let meta = file_handle.metadata()?;
+    let since_epoch = meta.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(CacheKey {
+        canonical_path: canonical.to_string_lossy().into_owned(),
+        len,
+        mtime_secs: since_epoch.as_secs(),
+        mtime_nanos: since_epoch.subsec_nanos(),
+    })
+}
+
+// One cache file per distinct path, named by a hash of the canonical path so
+// we don't have to sanitize arbitrary paths into filenames.
+fn cache_file_for(key: &CacheKey) -> Option<PathBuf> {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    key.canonical_path.hash(&mut hasher);
+    Some(cache_dir()?.join(format!("{:016x}.idx", hasher.finish())))
+}
+
+/// Look up a cached line index for `path`, validating it against the file's
+/// current canonical path, size, and mtime. Returns `None` on a miss, a
+/// disabled/unavailable cache dir, or a partial/corrupt cache file — callers
+/// fall back to a full rescan in every case.
+pub fn load(path: &Path) -> Option<CachedIndex> {
+    let key = key_for(path).ok()?;
+    let cache_path = cache_file_for(&key)?;
+    let bytes = std::fs::read(cache_path).ok()?;
+    let entry: CacheEntry = bincode::deserialize(&bytes).ok()?;
+
+    if entry.key != key {
+        return None;
+    }
+
+    Some(CachedIndex {
+        num_lines: entry.num_lines,
+        max_num_cols: entry.max_num_cols,
+        line_to_byte_idx: entry.line_to_byte_idx,
+        line_to_num_bytes: entry.line_to_num_bytes,
+        line_to_num_cols: entry.line_to_num_cols,
+    })
+}
+
+/// Write the line index to the cache under `key`, atomically (write to a
+/// sibling temp file, then rename) so a concurrent reader never observes a
+/// partially-written cache file. `key` should be captured at the same time
+/// as the scan that produced this data, via `key_for_snapshot`, rather than
+/// re-derived here — the scan can take a while, and the file may have grown
+/// further by the time this is called.
+pub fn save(
+    key: CacheKey,
+    num_lines: u64,
+    max_num_cols: u64,
+    line_to_byte_idx: &[u64],
+    line_to_num_bytes: &[u64],
+    line_to_num_cols: &[u64],
+) -> Result<(), Error> {
+    let cache_path =
+        cache_file_for(&key).ok_or_else(|| Error::new(ErrorKind::NotFound, "no cache dir"))?;
+    std::fs::create_dir_all(cache_path.parent().expect("cache path has a parent"))?;
+
+    let entry = CacheEntry {
+        key,
+        num_lines,
+        max_num_cols,
+        line_to_byte_idx: line_to_byte_idx.to_vec(),
+        line_to_num_bytes: line_to_num_bytes.to_vec(),
+        line_to_num_cols: line_to_num_cols.to_vec(),
+    };
+    let bytes = bincode::serialize(&entry).map_err(Error::other)?;
+
+    let tmp_path = cache_path.with_extension("idx.tmp");
+    std::fs::write(&tmp_path, &bytes)?;
+    std::fs::rename(&tmp_path, &cache_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // `save`/`load` resolve the cache directory from `XDG_CACHE_HOME`, a
+    // process-global env var, so tests that touch it must not run
+    // concurrently with each other or with each other's directories.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    struct TestEnv {
+        _lock: std::sync::MutexGuard<'static, ()>,
+        dir: PathBuf,
+    }
+
+    impl TestEnv {
+        fn new(name: &str) -> Self {
+            let lock = ENV_LOCK.lock().unwrap();
+            let dir = std::env::temp_dir().join(format!("glance-cache-test-{}-{name}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            unsafe {
+                std::env::set_var("XDG_CACHE_HOME", &dir);
+            }
+            TestEnv { _lock: lock, dir }
+        }
+
+        fn write_source(&self, name: &str, contents: &[u8]) -> PathBuf {
+            let path = self.dir.join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TestEnv {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn save_index(path: &Path) {
+        let file_handle = std::fs::File::open(path).unwrap();
+        let len = std::fs::metadata(path).unwrap().len();
+        let key = key_for_snapshot(path, &file_handle, len).unwrap();
+        save(key, 3, 10, &[0, 5, 9], &[4, 3, 1], &[4, 3, 1]).unwrap();
+    }
+
+    #[test]
+    fn load_after_save_round_trips() {
+        let env = TestEnv::new("roundtrip");
+        let path = env.write_source("a.txt", b"abcd\nefg\nh\n");
+
+        assert!(load(&path).is_none());
+        save_index(&path);
+
+        let cached = load(&path).unwrap();
+        assert_eq!(cached.num_lines, 3);
+        assert_eq!(cached.max_num_cols, 10);
+        assert_eq!(cached.line_to_byte_idx, vec![0, 5, 9]);
+        assert_eq!(cached.line_to_num_bytes, vec![4, 3, 1]);
+        assert_eq!(cached.line_to_num_cols, vec![4, 3, 1]);
+    }
+
+    #[test]
+    fn load_misses_once_the_file_changes() {
+        let env = TestEnv::new("staleness");
+        let path = env.write_source("b.txt", b"abcd\nefg\nh\n");
+        save_index(&path);
+        assert!(load(&path).is_some());
+
+        // Appending bytes changes the length (and usually the mtime), so
+        // the cached entry's key no longer matches -- it must not be served
+        // stale.
+        let mut contents = std::fs::read(&path).unwrap();
+        contents.extend_from_slice(b"more\n");
+        std::fs::write(&path, &contents).unwrap();
+
+        assert!(load(&path).is_none());
+    }
+
+    #[test]
+    fn load_misses_for_an_uncached_path() {
+        let env = TestEnv::new("miss");
+        let path = env.write_source("c.txt", b"xyz\n");
+        assert!(load(&path).is_none());
+    }
+}
@@ -1,35 +1,306 @@
+use crate::ansi;
+use crate::cache;
+use crate::width::{char_display_width, clip_char_to_cols};
 use memmap::Mmap;
 use simdutf8::basic::from_utf8;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::{cmp::max, io::Error};
+use syntect::highlighting::{
+    HighlightIterator, HighlightState, Highlighter, Style, Theme, ThemeSet,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxSet};
+
+// How often (in lines) we snapshot the syntect parse/highlight state so that
+// highlighting a line never requires replaying the whole file from the top.
+const HIGHLIGHT_CHECKPOINT_INTERVAL: u64 = 500;
 
 pub struct File {
-    mmap: Mmap,
+    // An `Arc` so readers (`full_line_text`, `len`, `read_bytes`) can grab
+    // their own owned reference and drop the lock immediately, instead of
+    // holding it for as long as they're working with the bytes — otherwise
+    // `build_linemap`, which iterates the whole mmap under this lock, would
+    // freeze every other reader for the length of the scan.
+    mmap: Mutex<Arc<Mmap>>,
+    file_handle: std::fs::File,
+    syntax_set: SyntaxSet,
+    theme: Theme,
+    syntax_name: String,
+    // Guards against overlapping build_linemap scans when follow mode
+    // triggers a remap before a previous scan has finished.
+    scanning: AtomicBool,
+    path: std::path::PathBuf,
+    cache_enabled: bool,
 }
 
 pub type FilePtr = Arc<File>;
 
 impl File {
-    pub fn open(filename: &str) -> Result<FilePtr, Error> {
-        let file = std::fs::File::open(filename)?;
-        let mmap_open = unsafe { Mmap::map(&file) };
-        match mmap_open {
-            Ok(mmap) => Ok(Arc::new(File { mmap })),
-            Err(e) => Err(e),
+    pub fn open(filename: &str, disable_cache: bool) -> Result<FilePtr, Error> {
+        let file_handle = std::fs::File::open(filename)?;
+        let mmap_open = unsafe { Mmap::map(&file_handle) };
+        let mmap = mmap_open?;
+
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let theme_set = ThemeSet::load_defaults();
+        let theme = theme_set.themes["base16-ocean.dark"].clone();
+
+        let ext = std::path::Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
+        let syntax_name = syntax_set
+            .find_syntax_by_extension(ext)
+            .unwrap_or_else(|| syntax_set.find_syntax_plain_text())
+            .name
+            .clone();
+
+        Ok(Arc::new(File {
+            mmap: Mutex::new(Arc::new(mmap)),
+            file_handle,
+            syntax_set,
+            theme,
+            syntax_name,
+            scanning: AtomicBool::new(false),
+            path: std::path::PathBuf::from(filename),
+            cache_enabled: !disable_cache,
+        }))
+    }
+
+    /// Populate `Metadata` instantly from the on-disk line-index cache if
+    /// `path` matches a cached entry, so the caller can skip the background
+    /// `build_linemap` scan. Returns `None` on a cache miss or when caching
+    /// is disabled, in which case the caller should scan as normal.
+    pub fn load_cached_metadata(&self) -> Option<MetadataPtr> {
+        if !self.cache_enabled {
+            return None;
+        }
+
+        let cached = cache::load(&self.path)?;
+        Some(Arc::new(Mutex::new(Metadata {
+            num_lines: cached.num_lines,
+            max_num_cols: cached.max_num_cols,
+            line_to_byte_idx: cached.line_to_byte_idx,
+            line_to_num_bytes: cached.line_to_num_bytes,
+            line_to_num_cols: cached.line_to_num_cols,
+            highlight_checkpoints: vec![],
+            tail_highlight_state: None,
+        })))
+    }
+
+    /// Attempt to claim the scan-in-progress flag; returns false if a scan
+    /// (initial or follow-triggered) is already running.
+    pub fn try_start_scan(&self) -> bool {
+        self.scanning
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Release a scan claimed via `try_start_scan` without having spawned a
+    /// scan thread for it (e.g. because `remap` itself failed).
+    pub fn stop_scan(&self) {
+        self.scanning.store(false, Ordering::Release);
+    }
+
+    fn syntax(&self) -> &syntect::parsing::SyntaxReference {
+        self.syntax_set
+            .find_syntax_by_name(&self.syntax_name)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text())
+    }
+
+    /// Owned reference to the current mmap, grabbed and returned without
+    /// holding `self.mmap`'s lock any longer than the clone itself.
+    fn mmap_snapshot(&self) -> Arc<Mmap> {
+        Arc::clone(&self.mmap.lock().unwrap())
+    }
+
+    /// Number of bytes currently mapped.
+    pub fn len(&self) -> u64 {
+        self.mmap_snapshot().len() as u64
+    }
+
+    /// Re-`mmap` the file, picking up any bytes appended since it was opened.
+    pub fn remap(&self) -> Result<(), Error> {
+        let new_mmap = unsafe { Mmap::map(&self.file_handle) }?;
+        *self.mmap.lock().unwrap() = Arc::new(new_mmap);
+        Ok(())
+    }
+
+    /// Read up to `len` raw bytes starting at `offset`, for hex-dump
+    /// rendering. Independent of the newline-based line map, so it works on
+    /// binary files where `from_utf8` would panic.
+    pub fn read_bytes(&self, offset: u64, len: usize) -> Vec<u8> {
+        let mmap = self.mmap_snapshot();
+        let start = std::cmp::min(offset as usize, mmap.len());
+        let end = std::cmp::min(start + len, mmap.len());
+        mmap[start..end].to_vec()
+    }
+
+    /// If the most recently recorded line didn't end in `\n` — the normal
+    /// state for a log's last line while it's being actively appended to —
+    /// it may have grown since the last scan. Pop it back out of the line
+    /// map, and any highlight checkpoint/tail state derived from parsing it,
+    /// so the upcoming scan re-reads it from its start byte instead of
+    /// treating it as permanently closed (otherwise a line that grows
+    /// mid-write, e.g. `"abc"` then `"abcdef\n"`, gets recorded as two lines,
+    /// `"abc"` and `"def\n"`, instead of one).
+    fn rewind_incomplete_last_line(&self, metadata: &mut Metadata) {
+        let (Some(&last_byte_idx), Some(&last_num_bytes)) = (
+            metadata.line_to_byte_idx.last(),
+            metadata.line_to_num_bytes.last(),
+        ) else {
+            return;
+        };
+        let last_end = (last_byte_idx + last_num_bytes) as usize;
+
+        let mmap = self.mmap_snapshot();
+        let terminated = last_end > 0 && mmap[last_end - 1] == b'\n';
+        drop(mmap);
+        if terminated {
+            return;
         }
+
+        let rolled_back_line = metadata.num_lines - 1;
+        metadata.num_lines = rolled_back_line;
+        metadata.line_to_byte_idx.pop();
+        metadata.line_to_num_bytes.pop();
+        metadata.line_to_num_cols.pop();
+        metadata
+            .highlight_checkpoints
+            .retain(|c| c.line < rolled_back_line);
+
+        // Replay from the nearest remaining checkpoint up to (not including)
+        // the rolled-back line, so `tail_highlight_state` reflects the
+        // parser as of that line's start byte, not its stale end.
+        let replay_from = metadata
+            .highlight_checkpoints
+            .iter()
+            .rev()
+            .find(|c| c.line <= rolled_back_line)
+            .map(|c| (c.line, c.parse_state.clone(), c.highlight_state.clone()));
+
+        metadata.tail_highlight_state = replay_from.map(|(checkpoint_line, mut parse_state, mut highlight_state)| {
+            let highlighter = Highlighter::new(&self.theme);
+            for line in checkpoint_line..rolled_back_line {
+                let text = self.full_line_text(metadata, line);
+                let ops = parse_state
+                    .parse_line(&text, &self.syntax_set)
+                    .unwrap_or_default();
+                for _ in HighlightIterator::new(&mut highlight_state, &ops, &text, &highlighter) {}
+            }
+            (parse_state, highlight_state)
+        });
+    }
+
+    /// Build highlight checkpoints for a line map that's already known (e.g.
+    /// loaded from the on-disk cache, which stores line boundaries but not
+    /// syntax-highlight state — see `cache::load`), by replaying each line's
+    /// text through the highlighter without rescanning the file for line
+    /// boundaries. Callers still gate this behind `try_start_scan`.
+    pub fn build_highlight_checkpoints(&self, metadata: &MetadataPtr) {
+        let num_lines = metadata.lock().unwrap().num_lines;
+
+        let highlighter = Highlighter::new(&self.theme);
+        let mut parse_state = ParseState::new(self.syntax());
+        let mut highlight_state = HighlightState::new(&highlighter, ScopeStack::new());
+
+        for line_no in 0..num_lines {
+            if line_no % HIGHLIGHT_CHECKPOINT_INTERVAL == 0 {
+                let mut metadata = metadata.lock().unwrap();
+                metadata.highlight_checkpoints.push(HighlightCheckpoint {
+                    line: line_no,
+                    parse_state: parse_state.clone(),
+                    highlight_state: highlight_state.clone(),
+                });
+            }
+
+            let chars = self.full_line_text(&metadata.lock().unwrap(), line_no);
+            let ops = parse_state
+                .parse_line(&chars, &self.syntax_set)
+                .unwrap_or_default();
+            for _ in HighlightIterator::new(&mut highlight_state, &ops, &chars, &highlighter) {}
+        }
+
+        metadata.lock().unwrap().tail_highlight_state = Some((parse_state, highlight_state));
+        self.scanning.store(false, Ordering::Release);
     }
 
+    /// Scan any bytes appended since the last scan and extend the line map
+    /// and highlight state in place, without rescanning from the start.
+    /// Called both for the initial scan and after `remap` grows the file.
     pub fn build_linemap(&self, metadata: &MetadataPtr) {
-        let lines = self
-            .mmap
-            .split_inclusive(|i| match char::from_u32(u32::from(i.clone())) {
+        let (mut total_bytes, mut line_no, saved_state) = {
+            let mut metadata = metadata.lock().unwrap();
+            self.rewind_incomplete_last_line(&mut metadata);
+
+            let total_bytes = match (
+                metadata.line_to_byte_idx.last(),
+                metadata.line_to_num_bytes.last(),
+            ) {
+                (Some(&byte_idx), Some(&num_bytes)) => byte_idx + num_bytes,
+                _ => 0,
+            };
+            (
+                total_bytes,
+                metadata.num_lines,
+                metadata.tail_highlight_state.clone(),
+            )
+        };
+
+        let highlighter = Highlighter::new(&self.theme);
+        let (mut parse_state, mut highlight_state) = saved_state.unwrap_or_else(|| {
+            (
+                ParseState::new(self.syntax()),
+                HighlightState::new(&highlighter, ScopeStack::new()),
+            )
+        });
+
+        // Clone the `Arc` and release the lock immediately: the scan below
+        // can run for seconds on a huge file, and must not block readers
+        // (`full_line_text`, `len`, `read_bytes`) for that whole time.
+        let mmap = self.mmap_snapshot();
+
+        // Capture the cache key against this exact snapshot (its length, not
+        // a later re-stat of the live file) so a file that keeps growing
+        // while this scan runs can't end up with a cache entry keyed to a
+        // size larger than what was actually scanned — which would make a
+        // later open at that size silently load a truncated index.
+        let cache_key = self.cache_enabled.then(|| {
+            cache::key_for_snapshot(&self.path, &self.file_handle, mmap.len() as u64)
+        });
+        let cache_key = cache_key.and_then(Result::ok);
+
+        let lines = mmap[total_bytes as usize..].split_inclusive(|i| {
+            match char::from_u32(u32::from(*i)) {
                 Some(c) => c == '\n',
                 None => false,
-            });
-
-        let mut total_bytes: u64 = 0;
+            }
+        });
 
         for line in lines {
+            // Binary/non-UTF8 files (hex mode's whole reason for existing,
+            // per `read_bytes`'s doc comment) would otherwise panic the
+            // background thread here. Stop the scan cleanly instead: what's
+            // already recorded stays usable, and hex mode never depended on
+            // this path anyway.
+            let Ok(chars) = from_utf8(line) else {
+                break;
+            };
+
+            if line_no % HIGHLIGHT_CHECKPOINT_INTERVAL == 0 {
+                let mut metadata = metadata.lock().unwrap();
+                metadata.highlight_checkpoints.push(HighlightCheckpoint {
+                    line: line_no,
+                    parse_state: parse_state.clone(),
+                    highlight_state: highlight_state.clone(),
+                });
+            }
+
+            let ops = parse_state
+                .parse_line(chars, &self.syntax_set)
+                .unwrap_or_default();
+            for _ in HighlightIterator::new(&mut highlight_state, &ops, chars, &highlighter) {}
+
             let mut metadata = metadata.lock().unwrap();
             metadata.num_lines += 1;
             metadata.line_to_byte_idx.push(total_bytes);
@@ -38,49 +309,134 @@ impl File {
             metadata.line_to_num_bytes.push(num_bytes);
             total_bytes += num_bytes;
 
-            let chars = from_utf8(line).unwrap();
             let mut num_cols: u64 = 0;
-            for _ in chars.chars() {
-                num_cols += 1;
+            for ch in chars.chars() {
+                num_cols += char_display_width(ch, num_cols);
             }
 
             metadata.line_to_num_cols.push(num_cols);
             metadata.max_num_cols = max(metadata.max_num_cols, num_cols);
+
+            line_no += 1;
         }
-    }
 
-    fn cols_to_bytes(s: &str, col_start: usize, col_end: usize) -> (usize, usize) {
-        let mut start: usize = s.len();
-        let mut end: usize = s.len();
-        let mut col = 0;
-        for (pos, _) in s.char_indices() {
-            if col == col_start {
-                start = pos;
-            }
-            if col == col_end {
-                end = pos;
+        {
+            let mut metadata = metadata.lock().unwrap();
+            metadata.tail_highlight_state = Some((parse_state, highlight_state));
+
+            if let Some(key) = cache_key {
+                let _ = cache::save(
+                    key,
+                    metadata.num_lines,
+                    metadata.max_num_cols,
+                    &metadata.line_to_byte_idx,
+                    &metadata.line_to_num_bytes,
+                    &metadata.line_to_num_cols,
+                );
             }
-            col += 1;
         }
-        (start, end)
+        self.scanning.store(false, Ordering::Release);
     }
 
-    pub fn get_text(&self, metadata: &Metadata, line: u64, col_start: u64, col_end: u64) -> &str {
-        use std::cmp::min;
-
+    fn full_line_text(&self, metadata: &Metadata, line: u64) -> String {
         let line_idx = line as usize;
         let byte_begin = metadata.line_to_byte_idx[line_idx] as usize;
         let byte_end = byte_begin + metadata.line_to_num_bytes[line_idx] as usize;
+        let mmap = self.mmap_snapshot();
+        from_utf8(&mmap[byte_begin..byte_end]).unwrap().to_string()
+    }
+
+    /// Highlight `line`, replaying from the nearest checkpoint at or before it,
+    /// then clip the resulting styled runs to the visible column range. Falls
+    /// back to unstyled text when no checkpoint is available yet — notably
+    /// when `metadata` came from the line-index cache, which doesn't carry
+    /// highlight state (see `cache::load`).
+    pub fn highlight_line(
+        &self,
+        metadata: &Metadata,
+        line: u64,
+        col_start: u64,
+        col_end: u64,
+    ) -> Vec<(Style, String)> {
+        let checkpoint = metadata
+            .highlight_checkpoints
+            .iter()
+            .rev()
+            .find(|c| c.line <= line);
+        let Some(checkpoint) = checkpoint else {
+            let text = self.full_line_text(metadata, line);
+            return Self::clip_ranges_to_cols(vec![(Style::default(), &text)], col_start, col_end);
+        };
+
+        let highlighter = Highlighter::new(&self.theme);
+        let mut parse_state = checkpoint.parse_state.clone();
+        let mut highlight_state = checkpoint.highlight_state.clone();
+        let mut result: Vec<(Style, String)> = vec![];
+
+        for cur_line in checkpoint.line..=line {
+            let text = self.full_line_text(metadata, cur_line);
+            let ops = parse_state
+                .parse_line(&text, &self.syntax_set)
+                .unwrap_or_default();
+            let ranges: Vec<(Style, &str)> =
+                HighlightIterator::new(&mut highlight_state, &ops, &text, &highlighter).collect();
+            if cur_line == line {
+                result = Self::clip_ranges_to_cols(ranges, col_start, col_end);
+            }
+        }
 
-        let chars = from_utf8(&self.mmap[byte_begin..byte_end]).unwrap();
-        let col_end = min(col_end as usize, chars.len());
-        let col_start = min(col_start as usize, col_end);
+        result
+    }
+
+    /// Whether `line` contains ANSI SGR escapes and should be rendered via
+    /// [`File::ansi_line`] instead of syntax highlighting.
+    pub fn has_ansi_escapes(&self, metadata: &Metadata, line: u64) -> bool {
+        self.full_line_text(metadata, line).contains('\u{1b}')
+    }
 
-        let (slice_start, slice_end) = Self::cols_to_bytes(chars, col_start, col_end);
-        &chars[slice_start..slice_end]
+    pub fn ansi_line(
+        &self,
+        metadata: &Metadata,
+        line: u64,
+        col_start: u64,
+        col_end: u64,
+    ) -> Vec<(ansi::AnsiStyle, String)> {
+        ansi::parse_sgr_line(&self.full_line_text(metadata, line), col_start, col_end)
+    }
+
+    /// Clip highlighted runs to the display-column range `[col_start,
+    /// col_end)`. Column clipping (tab stops, wide glyphs, straddling runs)
+    /// is handled by `width::clip_char_to_cols`, shared with
+    /// `ansi::parse_sgr_line`.
+    fn clip_ranges_to_cols(
+        ranges: Vec<(Style, &str)>,
+        col_start: u64,
+        col_end: u64,
+    ) -> Vec<(Style, String)> {
+        let mut result = vec![];
+        let mut col: u64 = 0;
+        for (style, text) in ranges {
+            let mut buf = String::new();
+            for ch in text.chars() {
+                col = clip_char_to_cols(&mut buf, ch, col, col_start, col_end);
+            }
+            if !buf.is_empty() {
+                result.push((style, buf));
+            }
+        }
+        result
     }
 }
 
+// A snapshot of the syntect parse/highlight state as of the start of `line`,
+// taken every HIGHLIGHT_CHECKPOINT_INTERVAL lines so that highlighting a line
+// only ever has to replay a bounded number of intervening lines.
+pub struct HighlightCheckpoint {
+    pub line: u64,
+    parse_state: ParseState,
+    highlight_state: HighlightState,
+}
+
 pub struct Metadata {
     pub num_lines: u64,
     pub max_num_cols: u64,
@@ -89,6 +445,12 @@ pub struct Metadata {
     line_to_byte_idx: Vec<u64>,
     line_to_num_bytes: Vec<u64>,
     line_to_num_cols: Vec<u64>,
+
+    highlight_checkpoints: Vec<HighlightCheckpoint>,
+
+    // Parse/highlight state as of `num_lines`, so build_linemap can resume
+    // scanning newly appended bytes without rescanning from the start.
+    tail_highlight_state: Option<(ParseState, HighlightState)>,
 }
 
 pub type MetadataPtr = Arc<Mutex<Metadata>>;
@@ -101,6 +463,8 @@ impl Metadata {
             line_to_byte_idx: vec![],
             line_to_num_bytes: vec![],
             line_to_num_cols: vec![],
+            highlight_checkpoints: vec![],
+            tail_highlight_state: None,
         };
         Arc::new(Mutex::new(m))
     }
@@ -2,32 +2,49 @@ use clap::Parser;
 use crossterm::event::EnableMouseCapture;
 use crossterm::event::{self, Event, KeyCode, MouseEventKind};
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
-use ratatui::text::{Line, Text};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
 use ratatui::widgets::block::Block;
 use ratatui::Frame;
+use syntect::highlighting::FontStyle;
 
 use std::fs::metadata;
 use std::sync::Arc;
 use std::thread;
 
+mod ansi;
+mod cache;
 mod file;
+mod width;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct CliOpts {
     file: String,
+
+    /// Disable the on-disk line-index cache (always rescan from the start).
+    #[arg(long)]
+    no_cache: bool,
 }
 
 fn main() {
     let cli = CliOpts::parse();
-    let file = match file::File::open(&cli.file) {
+    let file = match file::File::open(&cli.file, cli.no_cache) {
         Ok(f) => f,
         Err(e) => panic!("Failed to open file '{}': {}", cli.file, e),
     };
 
-    let metadata = file::Metadata::new();
-
-    launch_background_work(&file, &metadata);
+    let metadata = match file.load_cached_metadata() {
+        Some(metadata) => {
+            launch_highlight_only_work(&file, &metadata);
+            metadata
+        }
+        None => {
+            let metadata = file::Metadata::new();
+            launch_background_work(&file, &metadata);
+            metadata
+        }
+    };
 
     let mut terminal = ratatui::init();
     run(&mut terminal, &cli, &file, &metadata);
@@ -35,6 +52,10 @@ fn main() {
 }
 
 fn launch_background_work(file: &file::FilePtr, metadata: &file::MetadataPtr) {
+    if !file.try_start_scan() {
+        return;
+    }
+
     let file = Arc::clone(file);
     let metadata = Arc::clone(metadata);
 
@@ -43,6 +64,24 @@ fn launch_background_work(file: &file::FilePtr, metadata: &file::MetadataPtr) {
     });
 }
 
+/// On a cache hit, the line map is already known but carries no highlight
+/// state (see `cache::load`), so syntax highlighting would otherwise stay
+/// off for the whole session on exactly the huge files the cache targets.
+/// Build highlight checkpoints in the background instead, reusing the
+/// existing line map rather than rescanning the file for line boundaries.
+fn launch_highlight_only_work(file: &file::FilePtr, metadata: &file::MetadataPtr) {
+    if !file.try_start_scan() {
+        return;
+    }
+
+    let file = Arc::clone(file);
+    let metadata = Arc::clone(metadata);
+
+    thread::spawn(move || {
+        file.build_highlight_checkpoints(&metadata);
+    });
+}
+
 enum Command {
     Idle,
     Cmd(String),
@@ -54,13 +93,20 @@ struct UIState {
     cur_col: u64,
     filename: String,
     cmd: Command,
+    show_gutter: bool,
+    follow: bool,
+    hex_mode: bool,
+    // Bytes shown per hex-dump row; recomputed from the content area's width
+    // each time the hex view is rendered, and reused for row/seek math.
+    hex_bytes_per_line: u64,
 }
 
 impl UIState {
-    fn scroll_to_y(&mut self, metadata: &file::MetadataPtr, line: u64) {
-        let metadata = metadata.lock().unwrap();
-        let newpos = if metadata.num_lines > 0 {
-            std::cmp::min(line, metadata.num_lines - 1)
+    // `max_line` is the exclusive upper bound for `cur_line` — text lines in
+    // normal mode, hex-dump rows in hex mode (see `max_line` below).
+    fn scroll_to_y(&mut self, max_line: u64, line: u64) {
+        let newpos = if max_line > 0 {
+            std::cmp::min(line, max_line - 1)
         } else {
             0
         };
@@ -69,18 +115,13 @@ impl UIState {
 
     fn scroll_up(&mut self, amt: u64) {
         // Avoid underflow
-        let newpos: u64 = if amt > self.cur_line {
-            0
-        } else {
-            self.cur_line - amt
-        };
+        let newpos: u64 = self.cur_line.saturating_sub(amt);
         self.cur_line = newpos;
     }
 
-    fn scroll_down(&mut self, metadata: &file::MetadataPtr, amt: u64) {
-        let metadata = metadata.lock().unwrap();
-        let newpos = if metadata.num_lines > 0 {
-            std::cmp::min(self.cur_line + amt, metadata.num_lines - 1)
+    fn scroll_down(&mut self, max_line: u64, amt: u64) {
+        let newpos = if max_line > 0 {
+            std::cmp::min(self.cur_line + amt, max_line - 1)
         } else {
             0
         };
@@ -89,11 +130,7 @@ impl UIState {
 
     fn scroll_left(&mut self, amt: u64) {
         // Avoid underflow
-        let newpos: u64 = if amt > self.cur_col {
-            0
-        } else {
-            self.cur_col - amt
-        };
+        let newpos: u64 = self.cur_col.saturating_sub(amt);
         self.cur_col = newpos;
     }
 
@@ -119,27 +156,72 @@ fn run(
     cli: &CliOpts,
     file: &file::FilePtr,
     metadata: &file::MetadataPtr,
-) -> () {
+) {
     let mut ui = UIState {
         cur_line: 0,
         cur_col: 0,
         filename: cli.file.clone(),
         cmd: Command::Idle,
+        show_gutter: false,
+        follow: false,
+        hex_mode: false,
+        hex_bytes_per_line: 16,
     };
 
     let _ = crossterm::execute!(std::io::stdout(), EnableMouseCapture);
     loop {
+        check_follow(file, metadata, &mut ui);
+
         terminal
-            .draw(|f| render(f, file, metadata, &ui))
+            .draw(|f| render(f, file, metadata, &mut ui))
             .expect("failed to draw frame");
 
         if event::poll(std::time::Duration::from_millis(1000)).expect("failed to poll event") {
             let event = event::read().expect("failed to read event");
-            match handle_event(&event, file, metadata, &mut ui) {
-                EventResult::Exit => break,
-                _ => {}
-            };
+            if let EventResult::Exit = handle_event(&event, file, metadata, &mut ui) { break };
+        }
+    }
+}
+
+fn check_follow(file: &file::FilePtr, metadata_ptr: &file::MetadataPtr, ui: &mut UIState) {
+    if !ui.follow {
+        return;
+    }
+
+    let disk_len = metadata(&ui.filename).map(|m| m.len()).unwrap_or(0);
+    // Claim the scan flag *before* remapping: if a scan is already in flight
+    // (startup's build_linemap, or a cache hit's build_highlight_checkpoints),
+    // committing the remap without one to consume it would make file.len()
+    // report the new size forever, permanently hiding this growth from the
+    // `disk_len > file.len()` check on every later tick.
+    if disk_len > file.len() && file.try_start_scan() {
+        if file.remap().is_err() {
+            file.stop_scan();
+            return;
+        }
+        let file = Arc::clone(file);
+        let metadata_ptr = Arc::clone(metadata_ptr);
+        thread::spawn(move || {
+            file.build_linemap(&metadata_ptr);
+        });
+    }
+
+    ui.scroll_to_y(max_line(ui, file, metadata_ptr), u64::MAX);
+}
+
+/// Exclusive upper bound for `cur_line`: text lines in normal mode, hex-dump
+/// rows (derived from `mmap.len()`, independent of the newline-based line
+/// map) in hex mode.
+fn max_line(ui: &UIState, file: &file::FilePtr, metadata: &file::MetadataPtr) -> u64 {
+    if ui.hex_mode {
+        let len = file.len();
+        if len == 0 {
+            0
+        } else {
+            len.div_ceil(ui.hex_bytes_per_line)
         }
+    } else {
+        metadata.lock().unwrap().num_lines
     }
 }
 
@@ -157,7 +239,7 @@ fn handle_event(
     match event {
         Event::Key(key) => match (key.code, &mut ui.cmd) {
             (KeyCode::Enter, Command::Cmd(cmd)) => {
-                return parse_cmd(&cmd.clone(), metadata, ui);
+                return parse_cmd(&cmd.clone(), file, metadata, ui);
             }
             (KeyCode::Char(':'), Command::Idle) => {
                 ui.cmd = Command::Cmd(String::from(":"));
@@ -167,22 +249,29 @@ fn handle_event(
                 cmd.pop();
             }
             (KeyCode::Esc, Command::Cmd(_)) => ui.cmd = Command::Idle,
-            (KeyCode::Up, _) => ui.scroll_up(1),
-            (KeyCode::Down, _) => ui.scroll_down(metadata, 1),
+            (KeyCode::Char('F'), Command::Idle) => ui.follow = !ui.follow,
+            (KeyCode::Up, _) => {
+                ui.follow = false;
+                ui.scroll_up(1);
+            }
+            (KeyCode::Down, _) => ui.scroll_down(max_line(ui, file, metadata), 1),
             (KeyCode::Left, _) => ui.scroll_left(1),
             (KeyCode::Right, _) => ui.scroll_right(metadata, 1),
             _ => {}
         },
         Event::Mouse(mouse) => match (mouse.kind, &mut ui.cmd) {
-            (MouseEventKind::ScrollUp, _) => ui.scroll_up(1),
-            (MouseEventKind::ScrollDown, _) => ui.scroll_down(metadata, 1),
+            (MouseEventKind::ScrollUp, _) => {
+                ui.follow = false;
+                ui.scroll_up(1);
+            }
+            (MouseEventKind::ScrollDown, _) => ui.scroll_down(max_line(ui, file, metadata), 1),
             (MouseEventKind::ScrollLeft, _) => ui.scroll_left(1),
             (MouseEventKind::ScrollRight, _) => ui.scroll_right(metadata, 1),
             _ => {}
         },
         _ => {}
     };
-    return EventResult::Continue;
+    EventResult::Continue
 }
 
 fn try_parse_lineno(cmd: &str) -> Option<u64> {
@@ -191,31 +280,67 @@ fn try_parse_lineno(cmd: &str) -> Option<u64> {
             return Some(line);
         }
     }
-    return None;
+    None
 }
 
-fn parse_cmd(cmd: &str, metadata: &file::MetadataPtr, ui: &mut UIState) -> EventResult {
+fn try_parse_hex_offset(cmd: &str) -> Option<u64> {
+    if let Some(hex) = cmd.strip_prefix(":x") {
+        if !hex.is_empty() {
+            return u64::from_str_radix(hex, 16).ok();
+        }
+    }
+    None
+}
+
+fn parse_cmd(
+    cmd: &str,
+    file: &file::FilePtr,
+    metadata: &file::MetadataPtr,
+    ui: &mut UIState,
+) -> EventResult {
     if cmd == ":q" {
-        return EventResult::Exit;
+        EventResult::Exit
+    } else if cmd == ":set number" {
+        ui.show_gutter = true;
+        ui.cmd = Command::Idle;
+        EventResult::Continue
+    } else if cmd == ":set nonumber" {
+        ui.show_gutter = false;
+        ui.cmd = Command::Idle;
+        EventResult::Continue
+    } else if cmd == ":hex" {
+        ui.hex_mode = !ui.hex_mode;
+        ui.cmd = Command::Idle;
+        EventResult::Continue
+    } else if let Some(offset) = try_parse_hex_offset(cmd) {
+        ui.hex_mode = true;
+        let row = offset / ui.hex_bytes_per_line.max(1);
+        ui.scroll_to_y(max_line(ui, file, metadata), row);
+        ui.cmd = Command::Idle;
+        EventResult::Continue
     } else if let Some(lineno) = try_parse_lineno(cmd) {
         // We present the line number as 1-based, but should allow :0 as input
         let lineno = std::cmp::max(lineno, 1) - 1;
-        ui.scroll_to_y(metadata, lineno);
+        ui.scroll_to_y(max_line(ui, file, metadata), lineno);
         ui.cmd = Command::Idle;
-        return EventResult::Continue;
+        EventResult::Continue
     } else {
         ui.cmd = Command::Error(format!("Invalid command: '{}'", cmd));
-        return EventResult::Continue;
+        EventResult::Continue
     }
 }
 
-fn render(frame: &mut Frame, file: &file::FilePtr, metadata: &file::MetadataPtr, ui: &UIState) {
+fn render(frame: &mut Frame, file: &file::FilePtr, metadata: &file::MetadataPtr, ui: &mut UIState) {
     let vertical = Layout::default()
         .direction(Direction::Vertical)
         .constraints(vec![Constraint::Min(1), Constraint::Length(1)]);
     let [content_area, ui_area] = vertical.areas(frame.area());
 
-    render_content(frame, content_area, file, metadata, ui);
+    if ui.hex_mode {
+        render_hex_content(frame, content_area, file, ui);
+    } else {
+        render_content(frame, content_area, file, metadata, ui);
+    }
     render_ui(frame, ui_area, file, metadata, ui);
 }
 
@@ -226,30 +351,184 @@ fn render_content(
     metadata: &file::MetadataPtr,
     ui: &UIState,
 ) {
+    use ratatui::style::Stylize;
+
+    let num_lines = metadata.lock().unwrap().num_lines;
+    let gutter_width = if ui.show_gutter && num_lines > 0 {
+        num_lines.ilog10() as u16 + 1 + 1
+    } else {
+        0
+    };
+
+    let (gutter_area, text_area) = if gutter_width > 0 {
+        let horizontal = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Length(gutter_width), Constraint::Min(0)]);
+        let [gutter_area, text_area] = horizontal.areas(rect);
+        (Some(gutter_area), text_area)
+    } else {
+        (None, rect)
+    };
+
     let view_col_begin: u64 = ui.cur_col;
-    let view_col_end = view_col_begin + rect.width as u64;
+    let view_col_end = view_col_begin + text_area.width as u64;
 
     let view_line_begin = ui.cur_line;
-    let view_line_end = view_line_begin + rect.height as u64;
+    let view_line_end = view_line_begin + text_area.height as u64;
 
     let mut lines: Vec<Line> = vec![];
+    let mut gutter_lines: Vec<Line> = vec![];
 
     let metadata = metadata.lock().unwrap();
     for line_idx in view_line_begin..view_line_end {
         if line_idx >= metadata.num_lines {
             break;
         }
-        let line = Line::from(file.get_text(&metadata, line_idx, view_col_begin, view_col_end));
-        lines.push(line);
+        let spans: Vec<Span> = if file.has_ansi_escapes(&metadata, line_idx) {
+            file.ansi_line(&metadata, line_idx, view_col_begin, view_col_end)
+                .into_iter()
+                .map(|(style, text)| Span::styled(text, ansi_style_to_ratatui(style)))
+                .collect()
+        } else {
+            file.highlight_line(&metadata, line_idx, view_col_begin, view_col_end)
+                .into_iter()
+                .map(|(style, text)| Span::styled(text, syntect_style_to_ratatui(style)))
+                .collect()
+        };
+        lines.push(Line::from(spans));
+
+        if gutter_area.is_some() {
+            let num_width = (gutter_width as usize).saturating_sub(1);
+            let num = format!("{:>width$} ", line_idx + 1, width = num_width);
+            gutter_lines.push(Line::from(num).dim());
+        }
+    }
+    drop(metadata);
+
+    if let Some(gutter_area) = gutter_area {
+        frame.render_widget(Text::from(gutter_lines), gutter_area);
+    }
+    frame.render_widget(Text::from(lines), text_area);
+}
+
+// Offset (8 hex digits) + ": " + "| " separating the hex and ASCII columns.
+const HEX_ROW_OVERHEAD: u64 = 8 + 2 + 2;
+// Each byte costs 3 columns in the hex group ("XX ") plus 1 in the ASCII sidebar.
+const HEX_COLS_PER_BYTE: u64 = 4;
+
+fn compute_hex_bytes_per_line(width: u16) -> u64 {
+    let avail = (width as u64).saturating_sub(HEX_ROW_OVERHEAD);
+    let fitted = (avail / HEX_COLS_PER_BYTE).max(1);
+    if fitted >= 32 {
+        32
+    } else {
+        16
+    }
+}
+
+fn render_hex_content(frame: &mut Frame, rect: Rect, file: &file::FilePtr, ui: &mut UIState) {
+    ui.hex_bytes_per_line = compute_hex_bytes_per_line(rect.width);
+    let bytes_per_line = ui.hex_bytes_per_line;
+
+    let total_len = file.len();
+    let start_offset = ui.cur_line * bytes_per_line;
+
+    let mut lines: Vec<Line> = vec![];
+    for row in 0..rect.height as u64 {
+        let offset = start_offset + row * bytes_per_line;
+        if offset >= total_len {
+            break;
+        }
+
+        let row_len = std::cmp::min(bytes_per_line, total_len - offset) as usize;
+        let bytes = file.read_bytes(offset, row_len);
+
+        let mut hex_part = String::new();
+        let mut ascii_part = String::new();
+        for b in &bytes {
+            hex_part.push_str(&format!("{:02x} ", b));
+            ascii_part.push(if b.is_ascii_graphic() || *b == b' ' {
+                *b as char
+            } else {
+                '.'
+            });
+        }
+        for _ in bytes.len()..bytes_per_line as usize {
+            hex_part.push_str("   ");
+        }
+
+        lines.push(Line::from(format!(
+            "{:08x}: {}| {}",
+            offset, hex_part, ascii_part
+        )));
     }
 
     frame.render_widget(Text::from(lines), rect);
 }
 
+fn syntect_style_to_ratatui(style: syntect::highlighting::Style) -> Style {
+    let mut rstyle = Style::default()
+        .fg(Color::Rgb(
+            style.foreground.r,
+            style.foreground.g,
+            style.foreground.b,
+        ))
+        .bg(Color::Rgb(
+            style.background.r,
+            style.background.g,
+            style.background.b,
+        ));
+
+    if style.font_style.contains(FontStyle::BOLD) {
+        rstyle = rstyle.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        rstyle = rstyle.add_modifier(Modifier::ITALIC);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        rstyle = rstyle.add_modifier(Modifier::UNDERLINED);
+    }
+
+    rstyle
+}
+
+fn ansi_style_to_ratatui(style: ansi::AnsiStyle) -> Style {
+    let mut rstyle = Style::default();
+
+    if let Some(c) = ansi_color_to_ratatui(style.fg) {
+        rstyle = rstyle.fg(c);
+    }
+    if let Some(c) = ansi_color_to_ratatui(style.bg) {
+        rstyle = rstyle.bg(c);
+    }
+    if style.bold {
+        rstyle = rstyle.add_modifier(Modifier::BOLD);
+    }
+    if style.italic {
+        rstyle = rstyle.add_modifier(Modifier::ITALIC);
+    }
+    if style.underline {
+        rstyle = rstyle.add_modifier(Modifier::UNDERLINED);
+    }
+    if style.reverse {
+        rstyle = rstyle.add_modifier(Modifier::REVERSED);
+    }
+
+    rstyle
+}
+
+fn ansi_color_to_ratatui(color: ansi::AnsiColor) -> Option<Color> {
+    match color {
+        ansi::AnsiColor::Default => None,
+        ansi::AnsiColor::Indexed(n) => Some(Color::Indexed(n)),
+        ansi::AnsiColor::Rgb(r, g, b) => Some(Color::Rgb(r, g, b)),
+    }
+}
+
 fn render_ui(
     frame: &mut Frame,
     rect: Rect,
-    file: &file::FilePtr,
+    _file: &file::FilePtr,
     metadata: &file::MetadataPtr,
     ui: &UIState,
 ) {
@@ -267,14 +546,11 @@ fn render_ui(
     let line_no = ui.cur_line + 1;
     let col_no = ui.cur_col + 1;
 
-    let line_percent = if metadata.num_lines > 0 {
-        line_no * 100 / metadata.num_lines
-    } else {
-        0
-    };
+    let line_percent = (line_no * 100).checked_div(metadata.num_lines).unwrap_or(0);
+    let follow_tag = if ui.follow { "F " } else { "" };
     let linedescr = format!(
-        " {}% ☰ {}/{} ㏑:{} ",
-        line_percent, line_no, metadata.num_lines, col_no
+        " {}{}% ☰ {}/{} ㏑:{} ",
+        follow_tag, line_percent, line_no, metadata.num_lines, col_no
     );
     let linedescr_text = Text::from(linedescr).right_aligned();
 
@@ -0,0 +1,106 @@
+//! Display-column width and clipping shared by `file::File` (line layout, the
+//! gutter, horizontal scrolling, `clip_ranges_to_cols`) and
+//! `ansi::parse_sgr_line` (ANSI-escape column clipping), so every
+//! column-clipping path agrees on the same tab-stop, wide-glyph, and
+//! straddling-run rules.
+
+use unicode_width::UnicodeWidthChar;
+
+/// Tabs expand to the next multiple of this many display columns.
+pub const TAB_STOP: u64 = 8;
+
+/// Display width of `ch` if it started at display column `col`: tabs expand
+/// to the next `TAB_STOP` boundary, everything else uses its `unicode-width`
+/// column count (0 for combining marks, 2 for wide CJK).
+pub fn char_display_width(ch: char, col: u64) -> u64 {
+    if ch == '\t' {
+        TAB_STOP - (col % TAB_STOP)
+    } else {
+        UnicodeWidthChar::width(ch).unwrap_or(0) as u64
+    }
+}
+
+/// Append `ch`'s contribution to a column-clipped rendering of `buf`:
+/// nothing if it falls entirely outside `[col_start, col_end)`, padding
+/// spaces if it straddles a boundary (so columns stay aligned without
+/// splitting the glyph), or `ch` itself otherwise. Returns the display
+/// column `ch` ends at, to feed back in as `col` for the next character.
+pub fn clip_char_to_cols(buf: &mut String, ch: char, col: u64, col_start: u64, col_end: u64) -> u64 {
+    let char_end = col + char_display_width(ch, col);
+
+    if char_end > col_start && col < col_end {
+        if col < col_start {
+            for _ in 0..(char_end.min(col_end) - col_start) {
+                buf.push(' ');
+            }
+        } else if char_end > col_end {
+            for _ in 0..(col_end - col) {
+                buf.push(' ');
+            }
+        } else {
+            buf.push(ch);
+        }
+    }
+
+    char_end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tab_expands_to_next_stop() {
+        assert_eq!(char_display_width('\t', 0), 8);
+        assert_eq!(char_display_width('\t', 3), 5);
+        assert_eq!(char_display_width('\t', 8), 8);
+    }
+
+    #[test]
+    fn wide_glyph_counts_two_columns() {
+        assert_eq!(char_display_width('字', 0), 2);
+    }
+
+    #[test]
+    fn combining_mark_counts_zero_columns() {
+        assert_eq!(char_display_width('\u{0301}', 0), 0);
+    }
+
+    fn clip(text: &str, col_start: u64, col_end: u64) -> String {
+        let mut buf = String::new();
+        let mut col = 0;
+        for ch in text.chars() {
+            col = clip_char_to_cols(&mut buf, ch, col, col_start, col_end);
+        }
+        buf
+    }
+
+    #[test]
+    fn chars_fully_inside_the_range_pass_through() {
+        assert_eq!(clip("hello", 0, 5), "hello");
+    }
+
+    #[test]
+    fn chars_fully_outside_the_range_are_dropped() {
+        assert_eq!(clip("hello", 10, 20), "");
+    }
+
+    #[test]
+    fn wide_glyph_straddling_col_start_is_padded_not_split() {
+        // "字" occupies columns [0, 2); clipping to start at column 1 can't
+        // show half the glyph, so it's replaced with one padding space.
+        assert_eq!(clip("字x", 1, 10), " x");
+    }
+
+    #[test]
+    fn wide_glyph_straddling_col_end_is_padded_not_split() {
+        assert_eq!(clip("x字", 0, 2), "x ");
+    }
+
+    #[test]
+    fn tab_straddling_col_end_is_padded() {
+        // A tab at column 6 expands to column 8; clipping at column 7 can
+        // only show one of those two columns.
+        assert_eq!(clip("xxxxxx\ty", 0, 7), "xxxxxx ");
+    }
+}